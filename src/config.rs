@@ -0,0 +1,122 @@
+use crate::socd::ResolutionMode;
+use anyhow::{Context, Result, bail};
+use evdev::KeyCode;
+use std::path::Path;
+
+/// A pair of physically-opposing keys (e.g. `A`/`D` or `Left`/`Right`) whose
+/// presses are resolved so the game never sees both held at once.
+#[derive(Debug, Clone, Copy)]
+pub struct SocdPair {
+    pub first: KeyCode,
+    pub second: KeyCode,
+    pub mode: ResolutionMode,
+}
+
+/// Parses a mode keyword, e.g. `"last-wins"`, `"first-wins"`, `"neutral"`,
+/// or `"priority:KEY_D"`.
+fn parse_mode(text: &str, first: KeyCode, second: KeyCode) -> Result<ResolutionMode> {
+    if let Some(winner_name) = text.strip_prefix("priority:") {
+        let winner = parse_keycode(winner_name)?;
+        if winner != first && winner != second {
+            bail!("priority winner {winner_name:?} is not one of the pair's keys");
+        }
+        return Ok(ResolutionMode::Priority { winner });
+    }
+
+    Ok(match text {
+        "last-wins" => ResolutionMode::LastWins,
+        "first-wins" => ResolutionMode::FirstWins,
+        "neutral" => ResolutionMode::Neutral,
+        other => bail!("unknown resolution mode {other:?}"),
+    })
+}
+
+/// Parses a key name such as `"KEY_A"` or `"KEY_LEFT"` into a [`KeyCode`].
+pub fn parse_keycode(name: &str) -> Result<KeyCode> {
+    macro_rules! keycode_table {
+        ($($key:ident),* $(,)?) => {
+            match name {
+                $(stringify!($key) => KeyCode::$key,)*
+                other => bail!("unknown key name {other:?}"),
+            }
+        };
+    }
+
+    Ok(keycode_table!(
+        KEY_A, KEY_B, KEY_C, KEY_D, KEY_E, KEY_F, KEY_G, KEY_H, KEY_I, KEY_J, KEY_K, KEY_L, KEY_M,
+        KEY_N, KEY_O, KEY_P, KEY_Q, KEY_R, KEY_S, KEY_T, KEY_U, KEY_V, KEY_W, KEY_X, KEY_Y, KEY_Z,
+        KEY_0, KEY_1, KEY_2, KEY_3, KEY_4, KEY_5, KEY_6, KEY_7, KEY_8, KEY_9, KEY_UP, KEY_DOWN,
+        KEY_LEFT, KEY_RIGHT, KEY_SPACE,
+    ))
+}
+
+/// Loads SOCD key pairs from a simple line-based config file.
+///
+/// Each non-empty, non-comment (`#`) line holds two whitespace-separated key
+/// names and an optional resolution mode (`last-wins` if omitted), e.g.:
+///
+/// ```text
+/// KEY_A KEY_D
+/// KEY_W KEY_S first-wins
+/// KEY_UP KEY_DOWN neutral
+/// KEY_LEFT KEY_RIGHT priority:KEY_RIGHT
+/// ```
+pub fn load_socd_pairs(path: &Path) -> Result<Vec<SocdPair>> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+
+    let mut pairs = Vec::new();
+    for (lineno, raw_line) in text.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        let (Some(first_name), Some(second_name), mode_name, None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!(
+                "{}:{}: expected two key names and an optional mode, got {:?}",
+                path.display(),
+                lineno + 1,
+                raw_line
+            );
+        };
+
+        let first = parse_keycode(first_name)?;
+        let second = parse_keycode(second_name)?;
+        let mode = match mode_name {
+            Some(name) => parse_mode(name, first, second)?,
+            None => ResolutionMode::LastWins,
+        };
+
+        pairs.push(SocdPair {
+            first,
+            second,
+            mode,
+        });
+    }
+
+    if pairs.is_empty() {
+        bail!("{}: no SOCD pairs defined", path.display());
+    }
+
+    Ok(pairs)
+}
+
+/// The built-in WASD default, used when no `--config` is given.
+pub fn default_socd_pairs() -> Vec<SocdPair> {
+    vec![
+        SocdPair {
+            first: KeyCode::KEY_A,
+            second: KeyCode::KEY_D,
+            mode: ResolutionMode::LastWins,
+        },
+        SocdPair {
+            first: KeyCode::KEY_W,
+            second: KeyCode::KEY_S,
+            mode: ResolutionMode::LastWins,
+        },
+    ]
+}
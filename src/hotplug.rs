@@ -0,0 +1,61 @@
+use anyhow::{Context, Result};
+use inotify::{EventMask, Inotify, WatchMask};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::PathBuf;
+
+/// An entry appearing or disappearing under `/dev/input`. Not filtered to
+/// keyboards: every created or deleted name is reported.
+pub enum HotplugEvent {
+    Added(PathBuf),
+    Removed(PathBuf),
+}
+
+/// Watches `/dev/input` via inotify so newly connected keyboards can be
+/// grabbed, and disconnected ones dropped, without restarting the process.
+pub struct Hotplug {
+    inotify: Inotify,
+    buffer: [u8; 4096],
+}
+
+impl Hotplug {
+    pub fn new() -> Result<Self> {
+        let inotify = Inotify::init().context("failed to init inotify")?;
+        inotify
+            .watches()
+            .add("/dev/input", WatchMask::CREATE | WatchMask::DELETE)
+            .context("failed to watch /dev/input")?;
+        Ok(Self {
+            inotify,
+            buffer: [0; 4096],
+        })
+    }
+
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.inotify.as_raw_fd()
+    }
+
+    /// Drains pending inotify events into device add/remove notifications.
+    pub fn drain(&mut self) -> Result<Vec<HotplugEvent>> {
+        let events = match self.inotify.read_events(&mut self.buffer) {
+            Ok(events) => events,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("failed to read inotify events"),
+        };
+
+        let mut out = Vec::new();
+        for event in events {
+            let Some(name) = event.name else {
+                continue;
+            };
+            let path = PathBuf::from("/dev/input").join(name);
+
+            if event.mask.contains(EventMask::CREATE) {
+                out.push(HotplugEvent::Added(path));
+            } else if event.mask.contains(EventMask::DELETE) {
+                out.push(HotplugEvent::Removed(path));
+            }
+        }
+
+        Ok(out)
+    }
+}
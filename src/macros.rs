@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use evdev::{Device, EventType, InputEvent, KeyCode, uinput::VirtualDevice};
+use nix::poll::{PollFd, PollFlags, poll};
+use std::collections::HashSet;
+use std::io::Write;
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::path::Path;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::{Duration, Instant};
+
+/// Captures every `InputEvent` from `dev` to `path` until `running` goes
+/// false, storing each event's type, code, value, and the delay since the
+/// previous one so it can be replayed with the same timing by [`play`].
+pub fn record(dev: &mut Device, running: &Arc<AtomicBool>, path: &Path) -> Result<()> {
+    let mut file =
+        std::fs::File::create(path).with_context(|| format!("failed to create {:?}", path))?;
+
+    let raw_fd = dev.as_raw_fd();
+    // SAFETY: raw_fd comes from `dev`, which outlives this poll loop.
+    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(raw_fd) };
+    let mut poll_fds = [PollFd::new(borrowed_fd, PollFlags::POLLIN)];
+
+    println!("Recording to {:?}, press Ctrl+C to stop", path);
+    let mut last = Instant::now();
+
+    while running.load(Ordering::SeqCst) {
+        let ready = match poll(&mut poll_fds, 50u16) {
+            Ok(n) => n,
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        if ready == 0 {
+            continue;
+        }
+
+        let Some(revents) = poll_fds[0].revents() else {
+            continue;
+        };
+        if !revents.contains(PollFlags::POLLIN) {
+            continue;
+        }
+
+        for ev in dev.fetch_events()? {
+            let now = Instant::now();
+            let delay = now.duration_since(last);
+            last = now;
+            writeln!(
+                file,
+                "{} {} {} {}",
+                ev.event_type().0,
+                ev.code(),
+                ev.value(),
+                delay.as_nanos()
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replays a macro file written by [`record`] through `vdev`, sleeping for
+/// each recorded inter-event delay so the original timing is reproduced.
+pub fn play(vdev: &mut VirtualDevice, path: &Path) -> Result<()> {
+    for (ty, code, value, delay_ns) in parse_macro_file(path)? {
+        std::thread::sleep(Duration::from_nanos(delay_ns));
+        let ev = InputEvent::new(ty, code, value);
+        let _ = vdev.emit(&[ev]);
+    }
+
+    Ok(())
+}
+
+/// The distinct `KEY` codes a macro file references, in the order first
+/// seen. Used to size a `VirtualDevice`'s capabilities for `--play` without
+/// needing a real keyboard to copy them from.
+pub fn keys_used(path: &Path) -> Result<Vec<KeyCode>> {
+    let mut seen = HashSet::new();
+    let mut keys = Vec::new();
+
+    for (ty, code, _, _) in parse_macro_file(path)? {
+        if ty == EventType::KEY.0 && seen.insert(code) {
+            keys.push(KeyCode::new(code));
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Parses a macro file's `<type> <code> <value> <delay_ns>` lines.
+fn parse_macro_file(path: &Path) -> Result<Vec<(u16, u16, i32, u64)>> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+
+    let mut records = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+        let (Some(ty), Some(code), Some(value), Some(delay_ns), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            anyhow::bail!(
+                "{}:{}: malformed macro line {:?}",
+                path.display(),
+                lineno + 1,
+                line
+            );
+        };
+
+        records.push((
+            ty.parse().context("invalid event type")?,
+            code.parse().context("invalid event code")?,
+            value.parse().context("invalid event value")?,
+            delay_ns.parse().context("invalid delay")?,
+        ));
+    }
+
+    Ok(records)
+}
@@ -1,6 +1,14 @@
+mod config;
+mod hotplug;
+mod macros;
+mod socd;
+
 use anyhow::{Context, Result};
 use evdev::{Device, EventType, InputEvent, uinput::VirtualDevice};
+use hotplug::{Hotplug, HotplugEvent};
+use socd::{PairState, Side};
 use nix::poll::{PollFd, PollFlags, poll};
+use std::collections::HashMap;
 use std::io::Write;
 use std::os::fd::{AsRawFd, BorrowedFd};
 use std::{
@@ -11,6 +19,23 @@ use std::{
     },
 };
 
+/// Scans CLI args for `flag <path>` and returns the path, if present.
+fn parse_path_flag(args: impl Iterator<Item = String>, flag: &str) -> Option<PathBuf> {
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
+/// Whether `--all` was passed, grabbing every detected keyboard instead of
+/// prompting the user to pick one.
+fn parse_all_flag(mut args: impl Iterator<Item = String>) -> bool {
+    args.any(|arg| arg == "--all")
+}
+
 fn is_likely_keyboard(dev: &Device) -> bool {
     dev.supported_keys()
         .map(|keys| {
@@ -22,6 +47,32 @@ fn is_likely_keyboard(dev: &Device) -> bool {
 }
 
 fn main() -> Result<()> {
+    // ---------- config ----------
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config_path = parse_path_flag(args.iter().cloned(), "--config");
+    let record_path = parse_path_flag(args.iter().cloned(), "--record");
+    let play_path = parse_path_flag(args.iter().cloned(), "--play");
+    let grab_all = parse_all_flag(args.iter().cloned());
+    let pairs = match &config_path {
+        Some(path) => config::load_socd_pairs(path)?,
+        None => config::default_socd_pairs(),
+    };
+
+    // ---------- play ----------
+    // Playback only needs a virtual device, not a real keyboard grab: build
+    // its capabilities straight from the macro file and skip the
+    // enumerate/select/grab dance entirely.
+    if let Some(path) = &play_path {
+        let keys: evdev::AttributeSet<evdev::KeyCode> =
+            macros::keys_used(path)?.into_iter().collect();
+        let mut vdev = VirtualDevice::builder()?
+            .name("snap-tap-virtual")
+            .with_keys(&keys)?
+            .build()?;
+        macros::play(&mut vdev, path)?;
+        return Ok(());
+    }
+
     // ---------- enumerate keyboards ----------
     let mut keyboards = Vec::new();
 
@@ -30,7 +81,7 @@ fn main() -> Result<()> {
 
         if is_likely_keyboard(&dev) {
             let name = dev.name().unwrap_or("Unknown keyboard").to_string();
-            keyboards.push((path, name));
+            keyboards.push((path.0, name));
         }
     }
 
@@ -38,42 +89,58 @@ fn main() -> Result<()> {
         anyhow::bail!("No keyboards found");
     }
 
-    // ---------- user selection ----------
-    let items: Vec<String> = keyboards
-        .iter()
-        .map(|(p, n)| format!("{n} ({})", p.0.display()))
-        .collect();
-
-    for (idx, item) in items.iter().enumerate() {
-        println!("{}: {}", idx, item);
-    }
+    // ---------- grab ----------
+    let (mut device_paths, mut devices): (Vec<PathBuf>, Vec<Device>) = if grab_all {
+        keyboards
+            .iter()
+            .map(|(path, name)| {
+                println!("Grabbing {} ({})", name, path.display());
+                let mut dev = Device::open(path)?;
+                dev.grab()?;
+                Ok((path.clone(), dev))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip()
+    } else {
+        let items: Vec<String> = keyboards
+            .iter()
+            .map(|(p, n)| format!("{n} ({})", p.display()))
+            .collect();
 
-    let idx = {
-        let mut input = String::new();
-        print!("Select keyboard: ");
-        std::io::stdout().flush()?;
-        std::io::stdin().read_line(&mut input)?;
-        input.trim().parse::<usize>()?
-    };
+        for (idx, item) in items.iter().enumerate() {
+            println!("{}: {}", idx, item);
+        }
 
-    println!("Selected {}", idx);
+        let idx = {
+            let mut input = String::new();
+            print!("Select keyboard: ");
+            std::io::stdout().flush()?;
+            std::io::stdin().read_line(&mut input)?;
+            input.trim().parse::<usize>()?
+        };
 
-    println!("Waiting 3 seconds. Do not press any keys.");
-    std::thread::sleep(std::time::Duration::from_secs(3));
+        println!("Selected {}", idx);
 
-    let ((path, _), _): &((PathBuf, Device), String) = &keyboards[idx];
-    let mut dev = Device::open(path)?;
-    println!("Grabbing {}", path.display());
+        println!("Waiting 3 seconds. Do not press any keys.");
+        std::thread::sleep(std::time::Duration::from_secs(3));
 
-    // ---------- grab ----------
-    dev.grab()?;
+        let (path, _) = &keyboards[idx];
+        let mut dev = Device::open(path)?;
+        println!("Grabbing {}", path.display());
+        dev.grab()?;
+        (vec![path.clone()], vec![dev])
+    };
 
     // ---------- virtual keyboard ----------
-    let keys = dev.supported_keys().unwrap();
-    let mut vdev = VirtualDevice::builder()?
-        .name("snap-tap-virtual")
-        .with_keys(keys)?
-        .build()?;
+    // The virtual device must advertise every key any grabbed device can
+    // send; uinput capabilities are fixed at creation, so a hotplugged
+    // keyboard can only emit keys already covered by the devices seen here.
+    let mut vdev_builder = VirtualDevice::builder()?.name("snap-tap-virtual");
+    for dev in &devices {
+        vdev_builder = vdev_builder.with_keys(dev.supported_keys().unwrap())?;
+    }
+    let mut vdev = vdev_builder.build()?;
 
     // ---------- clean exit handling ----------
     let running = Arc::new(AtomicBool::new(true));
@@ -84,23 +151,61 @@ fn main() -> Result<()> {
         })?;
     }
 
+    // ---------- record ----------
+    // Bypasses SOCD processing entirely: dumps the grabbed device's raw
+    // events to a macro file. `--play` is handled earlier, before any
+    // device is grabbed.
+    if let Some(path) = &record_path {
+        macros::record(&mut devices[0], &running, path)?;
+        for dev in &mut devices {
+            dev.ungrab()?;
+        }
+        return Ok(());
+    }
+
     // ---------- state ----------
-    let mut a_down = false;
-    let mut d_down = false;
-    let mut w_down = false;
-    let mut s_down = false;
-
-    // prepare poll
-    let raw_fd = dev.as_raw_fd();
-    // SAFETY:
-    // - raw_fd comes from a live evdev::Device
-    // - dev outlives the poll loop
-    // - poll() does not take ownership of the FD
-    let borrowed_fd = unsafe { BorrowedFd::borrow_raw(raw_fd) };
-    let mut poll_fds = [PollFd::new(borrowed_fd, PollFlags::POLLIN)];
+    // Map each watched key to the index of its SocdPair, and track the
+    // physical pressed state of both keys in every pair.
+    let mut key_to_pair: HashMap<u16, usize> = HashMap::new();
+    for (idx, pair) in pairs.iter().enumerate() {
+        key_to_pair.insert(pair.first.code(), idx);
+        key_to_pair.insert(pair.second.code(), idx);
+    }
+    let mut pair_states: Vec<PairState> = (0..pairs.len()).map(|_| PairState::default()).collect();
+
+    // watch /dev/input so keyboards plugged in later are grabbed too, and
+    // ones unplugged mid-session are dropped instead of erroring out
+    let mut hotplug = Hotplug::new()?;
 
     // ---------- event loop ----------
     while running.load(Ordering::SeqCst) {
+        // Snapshot the device count before building this tick's poll set.
+        // `devices`/`device_paths` must not grow or shrink until after the
+        // read loop below: poll_fds[i] is only guaranteed to describe
+        // devices[i] for the `devices` this tick started with. Applying a
+        // hotplug Added/Removed mid-tick would shift later indices out from
+        // under the revents we already polled, so fetch_events() could be
+        // called on a device whose fd was never marked readable and block.
+        let device_count = devices.len();
+
+        // SAFETY: each raw fd comes from either a live evdev::Device in
+        // `devices` or `hotplug`, both of which outlive this tick; poll()
+        // does not take ownership of the FDs
+        let raw_fds: Vec<_> = devices
+            .iter()
+            .map(|d| d.as_raw_fd())
+            .chain(std::iter::once(hotplug.as_raw_fd()))
+            .collect();
+        let borrowed_fds: Vec<_> = raw_fds
+            .iter()
+            .map(|&fd| unsafe { BorrowedFd::borrow_raw(fd) })
+            .collect();
+        let mut poll_fds: Vec<_> = borrowed_fds
+            .iter()
+            .map(|fd| PollFd::new(*fd, PollFlags::POLLIN))
+            .collect();
+        let hotplug_idx = device_count;
+
         // wait up to 50 ms for input
         let ready = match poll(&mut poll_fds, 50u16) {
             Ok(n) => n,
@@ -113,87 +218,119 @@ fn main() -> Result<()> {
             continue;
         }
 
-        if let Some(revents) = poll_fds[0].revents() {
+        // ---- device reads: indices 0..device_count, matching poll_fds as
+        // built above for this tick ----
+        let mut disconnected = Vec::new();
+        for i in 0..device_count {
+            let Some(revents) = poll_fds[i].revents() else {
+                continue;
+            };
             if !revents.contains(PollFlags::POLLIN) {
                 continue;
             }
 
+            let dev = &mut devices[i];
             // safe: read will not block now
-            for ev in dev.fetch_events()? {
-                if ev.event_type() == EventType::KEY {
-                    match ev.code() {
-                        code if code == evdev::KeyCode::KEY_A.code() => {
-                            if ev.value() == 1 {
-                                a_down = true;
-                                emit(&mut vdev, evdev::KeyCode::KEY_A, 1);
-                                if d_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_D, 0);
-                                }
-                            } else if ev.value() == 0 {
-                                a_down = false;
-                                emit(&mut vdev, evdev::KeyCode::KEY_A, 0);
-                                if d_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_D, 1);
-                                }
-                            }
-                        }
-                        code if code == evdev::KeyCode::KEY_D.code() => {
-                            if ev.value() == 1 {
-                                d_down = true;
-                                emit(&mut vdev, evdev::KeyCode::KEY_D, 1);
-                                if a_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_A, 0);
-                                }
-                            } else if ev.value() == 0 {
-                                d_down = false;
-                                emit(&mut vdev, evdev::KeyCode::KEY_D, 0);
-                                if a_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_A, 1);
-                                }
-                            }
-                        }
-                        code if code == evdev::KeyCode::KEY_W.code() => {
-                            if ev.value() == 1 {
-                                w_down = true;
-                                emit(&mut vdev, evdev::KeyCode::KEY_W, 1);
-                                if s_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_S, 0);
-                                }
-                            } else if ev.value() == 0 {
-                                w_down = false;
-                                emit(&mut vdev, evdev::KeyCode::KEY_W, 0);
-                                if s_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_S, 1);
-                                }
-                            }
-                        }
-                        code if code == evdev::KeyCode::KEY_S.code() => {
-                            if ev.value() == 1 {
-                                s_down = true;
-                                emit(&mut vdev, evdev::KeyCode::KEY_S, 1);
-                                if w_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_W, 0);
-                                }
-                            } else if ev.value() == 0 {
-                                s_down = false;
-                                emit(&mut vdev, evdev::KeyCode::KEY_S, 0);
-                                if w_down {
-                                    emit(&mut vdev, evdev::KeyCode::KEY_W, 1);
-                                }
-                            }
+            // collected up front so `dev` is free to borrow again for the
+            // get_key_state() resync below
+            let events: Vec<InputEvent> = match dev.fetch_events() {
+                Ok(events) => events.collect(),
+                Err(_) => {
+                    // device vanished without an inotify DELETE arriving
+                    // first; drop it instead of crashing the whole session
+                    disconnected.push(i);
+                    continue;
+                }
+            };
+
+            // Set after a SYN_DROPPED until the next SYN_REPORT: per the
+            // kernel's contract, everything in between is unreliable and
+            // would re-desync the state `resync` just reconciled.
+            let mut discarding_after_drop = false;
+
+            for ev in events {
+                if discarding_after_drop {
+                    if ev.event_type() == EventType::SYNCHRONIZATION
+                        && ev.code() == evdev::SynchronizationCode::SYN_REPORT.0
+                    {
+                        discarding_after_drop = false;
+                    }
+                    continue;
+                }
+
+                if ev.event_type() == EventType::SYNCHRONIZATION
+                    && ev.code() == evdev::SynchronizationCode::SYN_DROPPED.0
+                {
+                    resync(dev, &pairs, &mut pair_states, &mut vdev)?;
+                    discarding_after_drop = true;
+                    continue;
+                }
+
+                if ev.event_type() != EventType::KEY {
+                    continue;
+                }
+
+                match key_to_pair.get(&ev.code()) {
+                    Some(&idx) => {
+                        if ev.value() > 1 {
+                            continue; // drop autorepeat once a SOCD pair owns this key
                         }
-                        _ => {
-                            // forward original event
-                            let _ = vdev.emit(&[ev]);
+
+                        let pair = &pairs[idx];
+                        let side = if ev.code() == pair.first.code() {
+                            Side::First
+                        } else {
+                            Side::Second
+                        };
+                        let down = ev.value() == 1;
+
+                        for (key, value) in pair_states[idx].handle(pair, side, down) {
+                            emit(&mut vdev, key, value);
                         }
                     }
+                    None => {
+                        // not a watched key: forward the original event, autorepeat included
+                        let _ = vdev.emit(&[ev]);
+                    }
                 }
             }
         }
+
+        // ---- hotplug: applied only after the read loop above, so the
+        // indices it just used stay aligned with the poll_fds built for
+        // this tick; any Added/Removed device is picked up by the poll set
+        // rebuilt on the next iteration ----
+        let hotplug_events = if poll_fds[hotplug_idx]
+            .revents()
+            .is_some_and(|r| r.contains(PollFlags::POLLIN))
+        {
+            hotplug.drain()?
+        } else {
+            Vec::new()
+        };
+
+        let (to_add, mut to_remove) = partition_hotplug_events(&device_paths, &hotplug_events);
+        for i in disconnected {
+            if !to_remove.contains(&i) {
+                to_remove.push(i);
+            }
+        }
+        to_remove.sort_unstable();
+        to_remove.dedup();
+        for i in to_remove.into_iter().rev() {
+            device_paths.remove(i);
+            devices.remove(i);
+        }
+
+        for path in &to_add {
+            try_grab_hotplugged(path, &mut device_paths, &mut devices);
+        }
     }
 
-    println!("Releasing keyboard");
-    dev.ungrab()?;
+    println!("Releasing keyboard(s)");
+    for dev in &mut devices {
+        dev.ungrab()?;
+    }
     Ok(())
 }
 
@@ -201,3 +338,170 @@ fn emit(vdev: &mut evdev::uinput::VirtualDevice, key: evdev::KeyCode, value: i32
     let ev = InputEvent::new(EventType::KEY.0, key.code(), value);
     let _ = vdev.emit(&[ev]);
 }
+
+/// Opens and grabs a keyboard that just appeared under `/dev/input`, adding
+/// it to `devices` so the main loop starts polling it. Non-keyboard devices
+/// (and opens that race the kernel still setting up the node) are ignored.
+fn try_grab_hotplugged(path: &PathBuf, device_paths: &mut Vec<PathBuf>, devices: &mut Vec<Device>) {
+    if device_paths.contains(path) {
+        return; // already grabbed, e.g. from startup enumeration
+    }
+
+    let Ok(mut dev) = Device::open(path) else {
+        return;
+    };
+    if !is_likely_keyboard(&dev) {
+        return;
+    }
+    if dev.grab().is_err() {
+        return;
+    }
+
+    println!("Hotplug: grabbed {}", path.display());
+    device_paths.push(path.clone());
+    devices.push(dev);
+}
+
+/// Splits a batch of [`HotplugEvent`]s against the currently tracked
+/// `device_paths` into newly-seen paths to grab and indices (into
+/// `device_paths`/`devices`) to drop. Kept free of `Device`/fd concerns so
+/// the add/remove bookkeeping can be exercised without a real keyboard.
+fn partition_hotplug_events(
+    device_paths: &[PathBuf],
+    events: &[HotplugEvent],
+) -> (Vec<PathBuf>, Vec<usize>) {
+    let mut to_add = Vec::new();
+    let mut to_remove = Vec::new();
+
+    for event in events {
+        match event {
+            HotplugEvent::Added(path) => {
+                if !device_paths.contains(path) && !to_add.contains(path) {
+                    to_add.push(path.clone());
+                }
+            }
+            HotplugEvent::Removed(path) => {
+                if let Some(idx) = device_paths.iter().position(|p| p == path) {
+                    println!("Hotplug: {} disconnected", path.display());
+                    to_remove.push(idx);
+                }
+            }
+        }
+    }
+
+    (to_add, to_remove)
+}
+
+/// Reconciles our per-pair pressed state against `dev`'s actual key-state
+/// snapshot after a SYN_DROPPED, emitting whatever release/press
+/// transitions bring the virtual device back in sync with physical reality.
+fn resync(
+    dev: &Device,
+    pairs: &[config::SocdPair],
+    pair_states: &mut [PairState],
+    vdev: &mut VirtualDevice,
+) -> Result<()> {
+    let key_state = dev.get_key_state()?;
+
+    for (pair, state) in pairs.iter().zip(pair_states.iter_mut()) {
+        for (side, key) in [(Side::First, pair.first), (Side::Second, pair.second)] {
+            let down = key_state.contains(key);
+            for (k, v) in state.handle(pair, side, down) {
+                emit(vdev, k, v);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn added_path_not_yet_tracked_is_queued_for_grab() {
+        let device_paths = vec![PathBuf::from("/dev/input/event0")];
+        let events = vec![HotplugEvent::Added(PathBuf::from("/dev/input/event1"))];
+
+        let (to_add, to_remove) = partition_hotplug_events(&device_paths, &events);
+
+        assert_eq!(to_add, vec![PathBuf::from("/dev/input/event1")]);
+        assert!(to_remove.is_empty());
+    }
+
+    #[test]
+    fn added_path_already_tracked_is_ignored() {
+        let device_paths = vec![PathBuf::from("/dev/input/event0")];
+        let events = vec![HotplugEvent::Added(PathBuf::from("/dev/input/event0"))];
+
+        let (to_add, to_remove) = partition_hotplug_events(&device_paths, &events);
+
+        assert!(to_add.is_empty());
+        assert!(to_remove.is_empty());
+    }
+
+    #[test]
+    fn removed_path_resolves_to_its_current_index() {
+        let device_paths = vec![
+            PathBuf::from("/dev/input/event0"),
+            PathBuf::from("/dev/input/event1"),
+            PathBuf::from("/dev/input/event2"),
+        ];
+        let events = vec![HotplugEvent::Removed(PathBuf::from("/dev/input/event1"))];
+
+        let (to_add, to_remove) = partition_hotplug_events(&device_paths, &events);
+
+        assert!(to_add.is_empty());
+        assert_eq!(to_remove, vec![1]);
+    }
+
+    #[test]
+    fn removed_path_not_tracked_is_ignored() {
+        let device_paths = vec![PathBuf::from("/dev/input/event0")];
+        let events = vec![HotplugEvent::Removed(PathBuf::from("/dev/input/event9"))];
+
+        let (to_add, to_remove) = partition_hotplug_events(&device_paths, &events);
+
+        assert!(to_add.is_empty());
+        assert!(to_remove.is_empty());
+    }
+
+    /// Regression test for a device appearing between this tick's `poll()`
+    /// and the read loop that follows it: `partition_hotplug_events` must
+    /// report the new path to grab *without* touching `device_paths` itself,
+    /// so callers can defer the actual `devices.push()` until after they're
+    /// done indexing this tick's `poll_fds` by the pre-hotplug device count.
+    #[test]
+    fn hotplug_event_during_read_loop_does_not_mutate_snapshot_used_by_caller() {
+        let device_paths = vec![PathBuf::from("/dev/input/event0")];
+        let device_count = device_paths.len();
+        let events = vec![HotplugEvent::Added(PathBuf::from("/dev/input/event1"))];
+
+        // Simulates: poll() returned with the hotplug fd readable, the read
+        // loop is about to run over `0..device_count`, and only afterwards
+        // do we ask what the hotplug batch means for `device_paths`.
+        let (to_add, to_remove) = partition_hotplug_events(&device_paths, &events);
+
+        assert_eq!(device_paths.len(), device_count, "snapshot must be untouched");
+        assert_eq!(to_add, vec![PathBuf::from("/dev/input/event1")]);
+        assert!(to_remove.is_empty());
+    }
+
+    #[test]
+    fn mixed_batch_adds_and_removes_independently() {
+        let device_paths = vec![
+            PathBuf::from("/dev/input/event0"),
+            PathBuf::from("/dev/input/event1"),
+        ];
+        let events = vec![
+            HotplugEvent::Removed(PathBuf::from("/dev/input/event0")),
+            HotplugEvent::Added(PathBuf::from("/dev/input/event2")),
+        ];
+
+        let (to_add, to_remove) = partition_hotplug_events(&device_paths, &events);
+
+        assert_eq!(to_add, vec![PathBuf::from("/dev/input/event2")]);
+        assert_eq!(to_remove, vec![0]);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::config::SocdPair;
+use evdev::KeyCode;
+
+/// Which half of a [`SocdPair`] an event belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    First,
+    Second,
+}
+
+/// How a [`SocdPair`] resolves simultaneous opposite-direction presses.
+#[derive(Debug, Clone, Copy)]
+pub enum ResolutionMode {
+    /// The most recently pressed key suppresses the other (current default).
+    LastWins,
+    /// The first key pressed stays active; the other is swallowed until it's released.
+    FirstWins,
+    /// While both are physically held, neither is emitted.
+    Neutral,
+    /// `winner` always suppresses the other key, regardless of press order.
+    Priority { winner: KeyCode },
+}
+
+/// Tracks the physical and last-emitted state of one [`SocdPair`].
+#[derive(Debug, Default)]
+pub struct PairState {
+    phys_first: bool,
+    phys_second: bool,
+    emitted_first: bool,
+    emitted_second: bool,
+    active: Option<Side>,
+}
+
+impl PairState {
+    /// Updates state for a press/release of one side of `pair` and returns
+    /// the `(key, value)` transitions that need to be emitted, if any.
+    pub fn handle(&mut self, pair: &SocdPair, side: Side, down: bool) -> Vec<(KeyCode, i32)> {
+        match side {
+            Side::First => self.phys_first = down,
+            Side::Second => self.phys_second = down,
+        }
+
+        let (want_first, want_second) = self.desired(pair, side, down);
+
+        let mut out = Vec::new();
+        if want_first != self.emitted_first {
+            out.push((pair.first, want_first as i32));
+            self.emitted_first = want_first;
+        }
+        if want_second != self.emitted_second {
+            out.push((pair.second, want_second as i32));
+            self.emitted_second = want_second;
+        }
+        out
+    }
+
+    fn desired(&mut self, pair: &SocdPair, side: Side, down: bool) -> (bool, bool) {
+        match pair.mode {
+            ResolutionMode::Neutral => {
+                if self.phys_first && self.phys_second {
+                    (false, false)
+                } else {
+                    (self.phys_first, self.phys_second)
+                }
+            }
+            ResolutionMode::LastWins => {
+                if down {
+                    self.active = Some(side);
+                } else if self.active == Some(side) {
+                    self.active = self.other_held(side);
+                }
+                self.apply_active()
+            }
+            ResolutionMode::FirstWins => {
+                if down && self.active.is_none() {
+                    self.active = Some(side);
+                } else if !down && self.active == Some(side) {
+                    self.active = self.other_held(side);
+                }
+                self.apply_active()
+            }
+            ResolutionMode::Priority { winner } => {
+                let winner_side = if winner == pair.first {
+                    Side::First
+                } else {
+                    Side::Second
+                };
+                self.active = if self.phys_first && self.phys_second {
+                    Some(winner_side)
+                } else if self.phys_first {
+                    Some(Side::First)
+                } else if self.phys_second {
+                    Some(Side::Second)
+                } else {
+                    None
+                };
+                self.apply_active()
+            }
+        }
+    }
+
+    /// If the side opposite `side` is still physically held, returns it.
+    fn other_held(&self, side: Side) -> Option<Side> {
+        match side {
+            Side::First if self.phys_second => Some(Side::Second),
+            Side::Second if self.phys_first => Some(Side::First),
+            _ => None,
+        }
+    }
+
+    fn apply_active(&self) -> (bool, bool) {
+        match self.active {
+            Some(Side::First) => (self.phys_first, false),
+            Some(Side::Second) => (false, self.phys_second),
+            None => (self.phys_first, self.phys_second),
+        }
+    }
+}